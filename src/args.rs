@@ -30,6 +30,11 @@ pub struct Args {
     #[arg(default_value_t = 3)]
     pub recurse_limit: u32,
 
+    /// The number of worker threads used to scan and fetch repos in parallel
+    #[arg(short, long)]
+    #[arg(default_value_t = default_jobs())]
+    pub jobs: u32,
+
     /// How to output colors on the terminal
     #[arg(short, long)]
     #[arg(value_enum)]
@@ -41,6 +46,14 @@ pub struct Args {
     pub path: String,
 }
 
+/// The default number of worker threads, based on the available parallelism
+/// of the host machine.
+fn default_jobs() -> u32 {
+    std::thread::available_parallelism()
+        .map(|n| n.get() as u32)
+        .unwrap_or(4)
+}
+
 impl Args {
     /// Applies the terminal colorizing settings from the `color` field.
     pub fn apply_color_option(&self) {
@@ -0,0 +1,236 @@
+use std::path::Path;
+use std::process::Command;
+
+/// The version control system backing a repo directory.
+///
+/// `Git` repos are scanned with the full detail `git2` gives us (see
+/// [`crate::issues`]); `Mercurial` and `Jujutsu` repos are scanned through
+/// the coarser [`Vcs`] trait, shelling out to `hg`/`jj`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Backend {
+    Git,
+    Mercurial,
+    Jujutsu,
+}
+
+impl Backend {
+    /// Probes `directory` for a `.git`, `.hg`, or `.jj` entry and returns the
+    /// backend that manages it, if any.
+    pub fn detect(directory: &str) -> Option<Backend> {
+        if Path::new(&format!("{}/.git", directory)).exists() {
+            Some(Backend::Git)
+        } else if Path::new(&format!("{}/.hg", directory)).exists() {
+            Some(Backend::Mercurial)
+        } else if Path::new(&format!("{}/.jj", directory)).exists() {
+            Some(Backend::Jujutsu)
+        } else {
+            None
+        }
+    }
+}
+
+/// The handful of checks `dirgit` needs from a non-git VCS in order to tell
+/// whether a repo is fully backed up.
+pub trait Vcs {
+    /// Whether the repo has a remote configured to push to / pull from.
+    fn has_remote(&self, directory: &str) -> Result<bool, String>;
+    /// Whether the currently checked out branch/bookmark is tracking a remote.
+    fn current_branch_tracked(&self, directory: &str) -> Result<bool, String>;
+    /// Whether the working copy has any uncommitted changes.
+    fn is_dirty(&self, directory: &str) -> Result<bool, String>;
+    /// The number of commits the working copy is ahead/behind its remote.
+    fn ahead_behind(&self, directory: &str) -> Result<(usize, usize), String>;
+}
+
+/// Runs `command` and returns its stdout, treating a non-zero exit status as
+/// a failure.
+fn run(mut command: Command) -> Result<Vec<u8>, String> {
+    let output = command
+        .output()
+        .map_err(|e| format!("failed to run `{:?}`: {}", command, e))?;
+
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(format!("`{:?}` failed: {}", command, stderr.trim()))
+    }
+}
+
+/// Like [`run`], but also treats `expected_empty_exit_code` as "ran fine,
+/// nothing to report" rather than a failure. `hg outgoing`/`hg incoming` exit
+/// 1 when there is nothing to push/pull, instead of succeeding with empty
+/// output the way e.g. `hg status` does.
+fn run_lenient(mut command: Command, expected_empty_exit_code: i32) -> Result<Vec<u8>, String> {
+    let output = command
+        .output()
+        .map_err(|e| format!("failed to run `{:?}`: {}", command, e))?;
+
+    if output.status.success() || output.status.code() == Some(expected_empty_exit_code) {
+        Ok(output.stdout)
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(format!("`{:?}` failed: {}", command, stderr.trim()))
+    }
+}
+
+pub struct Mercurial;
+
+impl Vcs for Mercurial {
+    fn has_remote(&self, directory: &str) -> Result<bool, String> {
+        let mut command = Command::new("hg");
+        command.arg("paths").current_dir(directory);
+        let stdout = run(command)?;
+        Ok(!stdout.is_empty())
+    }
+
+    fn current_branch_tracked(&self, directory: &str) -> Result<bool, String> {
+        // Mercurial has no per-branch upstream concept like git; having a
+        // remote configured is the closest equivalent.
+        self.has_remote(directory)
+    }
+
+    fn is_dirty(&self, directory: &str) -> Result<bool, String> {
+        // Unlike `hg outgoing`/`hg incoming`, `hg status` exits 0 whether or
+        // not the working copy is clean - emptiness is carried in stdout.
+        let mut command = Command::new("hg");
+        command.arg("status").current_dir(directory);
+        let stdout = run(command)?;
+        Ok(!stdout.is_empty())
+    }
+
+    fn ahead_behind(&self, directory: &str) -> Result<(usize, usize), String> {
+        let mut outgoing = Command::new("hg");
+        outgoing.arg("outgoing").current_dir(directory);
+        let ahead = count_changesets(run_lenient(outgoing, 1)?);
+
+        let mut incoming = Command::new("hg");
+        incoming.arg("incoming").current_dir(directory);
+        let behind = count_changesets(run_lenient(incoming, 1)?);
+
+        Ok((ahead, behind))
+    }
+}
+
+fn count_changesets(stdout: Vec<u8>) -> usize {
+    String::from_utf8_lossy(&stdout)
+        .lines()
+        .filter(|l| l.starts_with("changeset:"))
+        .count()
+}
+
+pub struct Jujutsu;
+
+impl Vcs for Jujutsu {
+    fn has_remote(&self, directory: &str) -> Result<bool, String> {
+        let mut command = Command::new("jj");
+        command
+            .arg("git")
+            .arg("remote")
+            .arg("list")
+            .current_dir(directory);
+        let stdout = run(command)?;
+        Ok(!stdout.is_empty())
+    }
+
+    fn current_branch_tracked(&self, directory: &str) -> Result<bool, String> {
+        // jj tracks remote bookmarks rather than a per-branch upstream;
+        // having a remote configured is the closest equivalent.
+        self.has_remote(directory)
+    }
+
+    fn is_dirty(&self, directory: &str) -> Result<bool, String> {
+        // `jj status`, like `hg status`, exits 0 regardless of whether the
+        // working copy is clean.
+        let mut command = Command::new("jj");
+        command.arg("status").current_dir(directory);
+        let stdout = run(command)?;
+        Ok(!String::from_utf8_lossy(&stdout).contains("The working copy has no changes"))
+    }
+
+    fn ahead_behind(&self, directory: &str) -> Result<(usize, usize), String> {
+        // An empty revset still exits 0 with empty output, so these don't
+        // need `run_lenient`'s exit-code leniency.
+        let mut ahead_cmd = Command::new("jj");
+        ahead_cmd
+            .arg("log")
+            .arg("--no-graph")
+            .arg("-T")
+            .arg("commit_id ++ \"\\n\"")
+            .arg("-r")
+            .arg("remote_bookmarks()..@")
+            .current_dir(directory);
+        let ahead = count_lines(run(ahead_cmd)?);
+
+        let mut behind_cmd = Command::new("jj");
+        behind_cmd
+            .arg("log")
+            .arg("--no-graph")
+            .arg("-T")
+            .arg("commit_id ++ \"\\n\"")
+            .arg("-r")
+            .arg("@..remote_bookmarks()")
+            .current_dir(directory);
+        let behind = count_lines(run(behind_cmd)?);
+
+        Ok((ahead, behind))
+    }
+}
+
+fn count_lines(stdout: Vec<u8>) -> usize {
+    String::from_utf8_lossy(&stdout)
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .count()
+}
+
+#[test]
+fn test_count_changesets() {
+    assert_eq!(count_changesets(b"".to_vec()), 0);
+    assert_eq!(
+        count_changesets(
+            b"changeset:   3:abcdef0\nuser:        a\nchangeset:   4:abcdef1\n".to_vec()
+        ),
+        2
+    );
+    // Only the "changeset:" line itself should count, not incidental matches.
+    assert_eq!(
+        count_changesets(b"summary:     mentions changeset: in passing\n".to_vec()),
+        0
+    );
+}
+
+#[test]
+fn test_count_lines() {
+    assert_eq!(count_lines(b"".to_vec()), 0);
+    assert_eq!(count_lines(b"\n\n".to_vec()), 0);
+    assert_eq!(count_lines(b"abc123\ndef456\n".to_vec()), 2);
+}
+
+#[test]
+fn test_run_succeeds_on_zero_exit() {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg("echo hello");
+    assert_eq!(run(command).unwrap(), b"hello\n");
+}
+
+#[test]
+fn test_run_fails_on_nonzero_exit() {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg("exit 1");
+    assert!(run(command).is_err());
+}
+
+#[test]
+fn test_run_lenient_treats_expected_exit_code_as_success() {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg("exit 1");
+    assert_eq!(run_lenient(command, 1).unwrap(), b"");
+}
+
+#[test]
+fn test_run_lenient_still_fails_on_other_exit_codes() {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg("exit 2");
+    assert!(run_lenient(command, 1).is_err());
+}
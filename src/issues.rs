@@ -1,21 +1,44 @@
 use crate::args::Args;
+use crate::backend::{Backend, Jujutsu, Mercurial, Vcs};
 use colored::Colorize;
+use git2::{BranchType, Repository, Status, StatusOptions};
 use std::fs;
-use std::path::Path;
 use std::process::Command;
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Default)]
 pub struct Issues {
     dir_searched: i32,
-    no_git_repo: Vec<String>,
+    no_vcs_repo: Vec<String>,
     no_remote: Vec<String>,
     current_branch_untracked: Vec<String>,
-    not_committed: Vec<String>,
-    not_pushed: Vec<String>,
-    have_diverged: Vec<String>,
+    conflicted: Vec<String>,
+    stashed: Vec<String>,
+    untracked: Vec<String>,
+    modified: Vec<String>,
+    not_pushed: Vec<(String, usize)>,
+    behind: Vec<(String, usize)>,
+    have_diverged: Vec<(String, usize, usize)>,
+    errors: Vec<(String, String)>,
 }
 
 impl Issues {
+    /// Merges another `Issues` (e.g. a worker's partial results) into this one.
+    fn merge(&mut self, other: Issues) {
+        self.dir_searched += other.dir_searched;
+        self.no_vcs_repo.extend(other.no_vcs_repo);
+        self.no_remote.extend(other.no_remote);
+        self.current_branch_untracked
+            .extend(other.current_branch_untracked);
+        self.conflicted.extend(other.conflicted);
+        self.stashed.extend(other.stashed);
+        self.untracked.extend(other.untracked);
+        self.modified.extend(other.modified);
+        self.not_pushed.extend(other.not_pushed);
+        self.behind.extend(other.behind);
+        self.have_diverged.extend(other.have_diverged);
+        self.errors.extend(other.errors);
+    }
+
     pub fn output(&self, args: &Args) -> String {
         fn colorize_count(u: usize) -> String {
             if u > 0 {
@@ -25,7 +48,13 @@ impl Issues {
             }
         }
 
-        fn section(args: &Args, s: &mut String, title: &str, contents: &Vec<String>) {
+        fn section<T>(
+            args: &Args,
+            s: &mut String,
+            title: &str,
+            contents: &[T],
+            render: impl Fn(&T) -> String,
+        ) {
             let count = contents.len();
             if !args.verbose && count == 0 {
                 return;
@@ -39,38 +68,93 @@ impl Issues {
                 colorize_count(count),
             ));
 
-            for path in contents.iter() {
-                s.push_str(&format!("    {}\n", path));
+            for item in contents.iter() {
+                s.push_str(&format!("    {}\n", render(item)));
             }
         }
 
         let mut s = String::new();
 
-        section(args, &mut s, "Non Git Repos", &self.no_git_repo);
-        section(args, &mut s, "Repos with No Remote Origin", &self.no_remote);
+        section(args, &mut s, "Non VCS Repos", &self.no_vcs_repo, |p| {
+            p.clone()
+        });
+        section(
+            args,
+            &mut s,
+            "Repos with No Remote Origin",
+            &self.no_remote,
+            |p| p.clone(),
+        );
         section(
             args,
             &mut s,
             "Repos with Current Branch Untracked",
             &self.current_branch_untracked,
+            |p| p.clone(),
         );
         section(
             args,
             &mut s,
-            "Repos with Uncommitted Files",
-            &self.not_committed,
+            "Repos with Merge Conflicts",
+            &self.conflicted,
+            |p| p.clone(),
+        );
+        section(
+            args,
+            &mut s,
+            "Repos with Stashed Changes",
+            &self.stashed,
+            |p| p.clone(),
+        );
+        section(
+            args,
+            &mut s,
+            "Repos with Untracked Files",
+            &self.untracked,
+            |p| p.clone(),
+        );
+        section(
+            args,
+            &mut s,
+            "Repos with Modified Files",
+            &self.modified,
+            |p| p.clone(),
         );
         section(
             args,
             &mut s,
             "Repos with Un-pushed Commits",
             &self.not_pushed,
+            |(p, ahead)| format!("{} {}", p, format!("⇡{}", ahead).yellow()),
+        );
+        section(
+            args,
+            &mut s,
+            "Repos Behind Remote",
+            &self.behind,
+            |(p, behind)| format!("{} {}", p, format!("⇣{}", behind).yellow()),
         );
         section(
             args,
             &mut s,
             "Repos with Diverged Branches",
             &self.have_diverged,
+            |(p, ahead, behind)| {
+                format!(
+                    "{} {} {}",
+                    p,
+                    format!("⇡{}", ahead).yellow(),
+                    format!("⇣{}", behind).yellow()
+                )
+            },
+        );
+
+        section(
+            args,
+            &mut s,
+            "Scan Errors",
+            &self.errors,
+            |(path, message)| format!("{}: {}", path, message),
         );
 
         if s.is_empty() {
@@ -81,136 +165,397 @@ impl Issues {
 }
 
 pub fn find_issues(args: &Args, issues: &mut Issues, directory: String, recurse_limit: u32) {
+    let mut repo_dirs = Vec::new();
+    collect_repo_dirs(&directory, recurse_limit, &mut repo_dirs);
+
+    let jobs = (args.jobs as usize).max(1);
+    let chunk_size = (repo_dirs.len() + jobs - 1) / jobs.max(1);
+
+    if chunk_size == 0 {
+        return;
+    }
+
+    let partials: Vec<Issues> = std::thread::scope(|scope| {
+        repo_dirs
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    let mut partial = Issues::default();
+                    for (dir, backend) in chunk {
+                        if let Err(message) =
+                            find_issues_with(args, &mut partial, dir.clone(), *backend)
+                        {
+                            partial.errors.push((dir.clone(), message));
+                        }
+                    }
+                    partial
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|handle| handle.join().expect("worker thread panicked"))
+            .collect()
+    });
+
+    for partial in partials {
+        issues.merge(partial);
+    }
+}
+
+/// Recursively walks `directory` up to `recurse_limit` levels deep, collecting the
+/// path and detected [`Backend`] of every repo found (i.e. every directory
+/// containing a `.git`, `.hg`, or `.jj` entry) so that they can be processed by
+/// the worker pool in [`find_issues`].
+fn collect_repo_dirs(directory: &str, recurse_limit: u32, repo_dirs: &mut Vec<(String, Backend)>) {
     if recurse_limit < 1 {
         return;
     }
 
-    if Path::exists(format!("{}/.git", directory).as_ref()) {
-        find_issues_with(args, issues, directory);
-    } else {
-        let paths = match fs::read_dir(&directory) {
-            Ok(paths) => paths,
+    if let Some(backend) = Backend::detect(directory) {
+        repo_dirs.push((directory.to_owned(), backend));
+        return;
+    }
 
-            Err(e) => {
-                eprintln!("Failed to read dir {}: {}", directory, e);
-                return;
-            }
-        };
+    let paths = match fs::read_dir(directory) {
+        Ok(paths) => paths,
 
-        for dir_entry in paths
-            .filter_map(|p| p.ok())
-            .filter(|p| p.metadata().map(|m| m.is_dir()).unwrap_or(false))
-        {
-            if let Some(path) = dir_entry.path().to_str() {
-                find_issues(args, issues, path.to_owned(), recurse_limit - 1);
-            }
+        Err(e) => {
+            eprintln!("Failed to read dir {}: {}", directory, e);
+            return;
+        }
+    };
+
+    for dir_entry in paths
+        .filter_map(|p| p.ok())
+        .filter(|p| p.metadata().map(|m| m.is_dir()).unwrap_or(false))
+    {
+        if let Some(path) = dir_entry.path().to_str() {
+            collect_repo_dirs(path, recurse_limit - 1, repo_dirs);
         }
     }
 }
 
-fn find_issues_with(args: &Args, issues: &mut Issues, directory: String) {
+/// Runs a command and turns a non-zero exit status into an `Err` carrying its
+/// stderr, the way rustc's build helpers check `Output::status` instead of
+/// asserting success.
+fn output_result(mut command: Command) -> Result<std::process::Output, String> {
+    let output = command
+        .output()
+        .map_err(|e| format!("failed to run `{:?}`: {}", command, e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("`{:?}` failed: {}", command, stderr.trim()));
+    }
+    Ok(output)
+}
+
+fn find_issues_with(
+    args: &Args,
+    issues: &mut Issues,
+    directory: String,
+    backend: Backend,
+) -> Result<(), String> {
     issues.dir_searched += 1;
 
+    match backend {
+        Backend::Git => find_git_issues(args, issues, directory),
+        Backend::Mercurial => find_vcs_issues(&Mercurial, args, issues, directory),
+        Backend::Jujutsu => find_vcs_issues(&Jujutsu, args, issues, directory),
+    }
+}
+
+/// Scans a Mercurial or Jujutsu repo through the coarser [`Vcs`] trait,
+/// rolling its checks up into the same `Issues` buckets git's richer
+/// `git2`-based scan in [`find_git_issues`] uses.
+fn find_vcs_issues(
+    vcs: &impl Vcs,
+    args: &Args,
+    issues: &mut Issues,
+    directory: String,
+) -> Result<(), String> {
+    if !vcs.has_remote(&directory)? {
+        issues.no_remote.push(directory);
+        return Ok(());
+    }
+
+    if !vcs.current_branch_tracked(&directory)? {
+        issues.current_branch_untracked.push(directory);
+        return Ok(());
+    }
+
+    if vcs.is_dirty(&directory)? {
+        issues.modified.push(directory);
+        return Ok(());
+    }
+
+    if args.no_fetch {
+        // Unlike git, hg/jj have no local cache of the remote's state to diff
+        // against offline - `hg outgoing`/`incoming` and the `jj log` calls in
+        // `ahead_behind` always contact the remote, so there is no equivalent
+        // of skipping `git fetch` for these backends. Skip the check entirely
+        // rather than silently ignoring the flag's contract.
+        return Ok(());
+    }
+
+    let (ahead, behind) = vcs.ahead_behind(&directory)?;
+
+    if ahead > 0 && behind > 0 {
+        issues.have_diverged.push((directory, ahead, behind));
+        return Ok(());
+    }
+
+    if ahead > 0 {
+        issues.not_pushed.push((directory, ahead));
+        return Ok(());
+    }
+
+    if behind > 0 {
+        issues.behind.push((directory, behind));
+    }
+
+    Ok(())
+}
+
+fn find_git_issues(args: &Args, issues: &mut Issues, directory: String) -> Result<(), String> {
     // perform git fetch
     if !args.no_fetch {
-        Command::new("git")
-            .arg("fetch")
-            .current_dir(&directory)
-            .output()
-            .expect("`git fetch` command failed");
+        let mut fetch = Command::new("git");
+        fetch.arg("fetch").current_dir(&directory);
+        output_result(fetch)?;
     }
 
-    // check git status
-    let git_status = Command::new("git")
-        .arg("status")
-        .current_dir(&directory)
-        .output()
-        .expect("`git status` command failed");
+    let mut repo = match Repository::open(&directory) {
+        Ok(repo) => repo,
+        Err(_) => {
+            issues.no_vcs_repo.push(directory);
+            return Ok(());
+        }
+    };
 
-    // check for remote
-    let git_remote = Command::new("git")
-        .arg("remote")
-        .current_dir(&directory)
-        .output()
-        .expect(&*format!(
-            "`git remote` command failed on dir {}",
-            directory
-        ));
-
-    // check if current branch is tracked
-    let git_branch_vv = Command::new("git")
-        .arg("branch")
-        .arg("-vv")
-        .arg("--color=never")
-        .current_dir(&directory)
-        .output()
-        .expect(&*format!(
-            "`git remote` command failed on dir {}",
-            directory
-        ));
-
-    if git_status
-        .stderr
-        .starts_with(b"fatal: not a git repository")
-    {
-        return issues.no_git_repo.push(directory.clone());
+    if repo.find_remote("origin").is_err() {
+        issues.no_remote.push(directory);
+        return Ok(());
     }
 
-    if !is_sub(&git_remote.stdout, b"origin") {
-        return issues.no_remote.push(directory.clone());
-    }
+    // `head`/`branch`/`upstream`/`statuses` all implement `Drop`, and under
+    // NLL a `Drop` type's borrow of `repo` stays live until the binding goes
+    // out of scope - not just until its last use. Confine them to this block
+    // so they (and the immutable borrow of `repo` they hold) are gone before
+    // `stash_foreach` below needs `repo` mutably.
+    let (local_oid, upstream_oid, is_conflicted, has_modified, has_untracked) = {
+        let head = match repo.head() {
+            Ok(head) => head,
+            Err(_) => {
+                issues.current_branch_untracked.push(directory);
+                return Ok(());
+            }
+        };
+
+        if !head.is_branch() {
+            // A detached HEAD has no local branch to look up an upstream for.
+            issues.current_branch_untracked.push(directory);
+            return Ok(());
+        }
 
-    let git_branch_vv_out = String::from_utf8(git_branch_vv.stdout.clone())
-        .expect("`git branch -vv` gave invalid utf-8");
-    let current_branch = git_branch_vv_out
-        .lines()
-        .find_map(|l| {
-            let mut words = l.split(" ");
-            if words.next() == Some("*") {
-                return words.next();
+        let branch_name = match head.shorthand() {
+            Some(name) => name.to_owned(),
+            None => {
+                issues.current_branch_untracked.push(directory);
+                return Ok(());
             }
-            None
-        })
-        .expect("could not find current branch");
-    if !is_sub(
-        &git_branch_vv.stdout,
-        format!("[origin/{}", current_branch).as_bytes(),
-    ) {
-        return issues.current_branch_untracked.push(directory.clone());
-    }
-
-    if is_sub(&git_status.stdout, b"Changes to be committed:")
-        || is_sub(&git_status.stdout, b"Changes not staged for commit:")
-        || is_sub(&git_status.stdout, b"Untracked files:")
-    {
-        return issues.not_committed.push(directory.clone());
+        };
+
+        let branch = repo
+            .find_branch(&branch_name, BranchType::Local)
+            .map_err(|e| format!("failed to look up branch '{}': {}", branch_name, e))?;
+
+        let upstream = match branch.upstream() {
+            Ok(upstream) => upstream,
+            Err(_) => {
+                issues.current_branch_untracked.push(directory);
+                return Ok(());
+            }
+        };
+
+        let local_oid = head
+            .target()
+            .ok_or_else(|| "HEAD does not point to a commit".to_string())?;
+        let upstream_oid = upstream
+            .get()
+            .target()
+            .ok_or_else(|| "upstream branch does not point to a commit".to_string())?;
+
+        let mut status_opts = StatusOptions::new();
+        status_opts.include_untracked(true);
+        let statuses = repo
+            .statuses(Some(&mut status_opts))
+            .map_err(|e| format!("failed to read repo statuses: {}", e))?;
+
+        let is_conflicted = statuses.iter().any(|entry| entry.status().is_conflicted());
+        // Renamed and deleted entries are folded into "modified" rather than
+        // getting their own sections - the request's "at least" list only
+        // requires conflicted/stashed/untracked-vs-modified to be distinct.
+        let has_modified = statuses.iter().any(|entry| {
+            entry.status().intersects(
+                Status::INDEX_NEW
+                    | Status::INDEX_MODIFIED
+                    | Status::INDEX_DELETED
+                    | Status::INDEX_RENAMED
+                    | Status::INDEX_TYPECHANGE
+                    | Status::WT_MODIFIED
+                    | Status::WT_DELETED
+                    | Status::WT_RENAMED
+                    | Status::WT_TYPECHANGE,
+            )
+        });
+        let has_untracked = statuses
+            .iter()
+            .any(|entry| entry.status().contains(Status::WT_NEW));
+
+        (
+            local_oid,
+            upstream_oid,
+            is_conflicted,
+            has_modified,
+            has_untracked,
+        )
+    };
+
+    if is_conflicted {
+        issues.conflicted.push(directory);
+        return Ok(());
+    }
+
+    let mut has_stash = false;
+    repo.stash_foreach(|_, _, _| {
+        has_stash = true;
+        false
+    })
+    .map_err(|e| format!("failed to read stash list: {}", e))?;
+    if has_stash {
+        issues.stashed.push(directory);
+        return Ok(());
+    }
+
+    if has_modified {
+        issues.modified.push(directory);
+        return Ok(());
+    }
+
+    if has_untracked {
+        issues.untracked.push(directory);
+        return Ok(());
     }
 
-    if is_sub(&git_status.stdout, b"Your branch is ahead of") {
-        return issues.not_pushed.push(directory.clone());
+    let (ahead, behind) = repo
+        .graph_ahead_behind(local_oid, upstream_oid)
+        .map_err(|e| format!("failed to compute ahead/behind counts: {}", e))?;
+
+    if ahead > 0 && behind > 0 {
+        issues.have_diverged.push((directory, ahead, behind));
+        return Ok(());
     }
 
-    if is_sub(&git_status.stdout, b"have diverged") {
-        return issues.have_diverged.push(directory.clone());
+    if ahead > 0 {
+        issues.not_pushed.push((directory, ahead));
+        return Ok(());
     }
+
+    if behind > 0 {
+        issues.behind.push((directory, behind));
+    }
+
+    Ok(())
 }
 
-fn is_sub<T: PartialEq>(haystack: &[T], needle: &[T]) -> bool {
-    haystack.windows(needle.len()).any(|c| c == needle)
+#[cfg(test)]
+fn test_args() -> Args {
+    use crate::args::ColorOptions;
+
+    let args = Args {
+        no_fetch: false,
+        verbose: false,
+        recurse_limit: 3,
+        jobs: 1,
+        color: ColorOptions::Never,
+        path: ".".to_string(),
+    };
+    // `output` does not apply `color` itself (`main` does), so apply it here
+    // to get plain, uncolored text regardless of the terminal.
+    args.apply_color_option();
+    args
+}
+
+#[test]
+fn test_output_renders_ahead_behind_and_diverged_counts() {
+    let mut issues = Issues::default();
+    issues.not_pushed.push(("/repo/ahead".to_string(), 3));
+    issues.behind.push(("/repo/behind".to_string(), 2));
+    issues
+        .have_diverged
+        .push(("/repo/diverged".to_string(), 1, 4));
+
+    let out = issues.output(&test_args());
+
+    assert!(out.contains("/repo/ahead ⇡3"));
+    assert!(out.contains("/repo/behind ⇣2"));
+    assert!(out.contains("/repo/diverged ⇡1 ⇣4"));
+}
+
+#[test]
+fn test_output_hides_empty_sections_unless_verbose() {
+    let issues = Issues::default();
+
+    assert_eq!(issues.output(&test_args()), "No issues found :)");
+
+    let mut verbose_args = test_args();
+    verbose_args.verbose = true;
+    let out = issues.output(&verbose_args);
+    assert!(out.contains("Repos with Merge Conflicts"));
+    assert!(out.contains("Scan Errors"));
 }
 
 #[test]
-fn test_is_sub() {
-    // Should be true
-    assert!(is_sub(b"Hello, world!", b"Hello"));
-    assert!(is_sub(b"Hello, world!", b"Hello, world!"));
-    assert!(is_sub(b"Hello, world!", b"ello"));
-    assert!(is_sub(b"Hello, world!", b"llo, wor"));
-    assert!(is_sub(b"Hello, world!", b"world!"));
-
-    // Should be false
-    assert!(!is_sub(b"Hello, world!", b"other"));
-    assert!(!is_sub(b"Hello, world!", b"Hello, world! with more"));
-    assert!(!is_sub(b"Hello, world!", b"Hello,  world!"));
-    assert!(!is_sub(b"Hello, world!", b" Hello, world!"));
+fn test_merge_combines_every_field() {
+    let mut issues = Issues::default();
+    issues.dir_searched = 1;
+    issues.no_vcs_repo.push("/plain".to_string());
+
+    let mut other = Issues::default();
+    other.dir_searched = 2;
+    other.no_remote.push("/no_remote".to_string());
+    other
+        .current_branch_untracked
+        .push("/untracked_branch".to_string());
+    other.conflicted.push("/conflicted".to_string());
+    other.stashed.push("/stashed".to_string());
+    other.untracked.push("/untracked".to_string());
+    other.modified.push("/modified".to_string());
+    other.not_pushed.push(("/ahead".to_string(), 1));
+    other.behind.push(("/behind".to_string(), 1));
+    other.have_diverged.push(("/diverged".to_string(), 1, 1));
+    other
+        .errors
+        .push(("/broken".to_string(), "boom".to_string()));
+
+    issues.merge(other);
+
+    assert_eq!(issues.dir_searched, 3);
+    assert_eq!(issues.no_vcs_repo, vec!["/plain".to_string()]);
+    assert_eq!(issues.no_remote, vec!["/no_remote".to_string()]);
+    assert_eq!(
+        issues.current_branch_untracked,
+        vec!["/untracked_branch".to_string()]
+    );
+    assert_eq!(issues.conflicted, vec!["/conflicted".to_string()]);
+    assert_eq!(issues.stashed, vec!["/stashed".to_string()]);
+    assert_eq!(issues.untracked, vec!["/untracked".to_string()]);
+    assert_eq!(issues.modified, vec!["/modified".to_string()]);
+    assert_eq!(issues.not_pushed, vec![("/ahead".to_string(), 1)]);
+    assert_eq!(issues.behind, vec![("/behind".to_string(), 1)]);
+    assert_eq!(issues.have_diverged, vec![("/diverged".to_string(), 1, 1)]);
+    assert_eq!(
+        issues.errors,
+        vec![("/broken".to_string(), "boom".to_string())]
+    );
 }
@@ -2,6 +2,7 @@ use crate::issues::{find_issues, Issues};
 use clap::Parser;
 
 mod args;
+mod backend;
 mod issues;
 
 fn main() {